@@ -0,0 +1,236 @@
+use std::io::Cursor;
+
+use mp4::{BoxHeader, BoxType, EmsgBox, FtypBox, MoofBox, MoovBox, ReadBox, SidxBox, StypBox};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{
+    error::Fmp4ParseError,
+    segment::{Chunk, InitialSegment, LeadingBox, MediaSegment},
+    Result,
+};
+
+/// Async counterpart to [`crate::Segment`] for streaming ingestion over a
+/// [`tokio::io::AsyncRead`] source.
+///
+/// fMP4 is strictly sequential (`ftyp`→`moov` for init; `styp` then repeating
+/// `moof`→`mdat` for media), so unlike [`crate::Segment`] this never needs
+/// `Seek`: each box header is read, its body buffered into a reusable
+/// scratch `Vec`, then parsed in-memory with the existing synchronous
+/// `ReadBox`/`read_box` machinery.
+pub trait AsyncSegment: Sized {
+    async fn read<R: AsyncRead + Unpin + Send>(reader: &mut R) -> Result<Self>;
+}
+
+/// Reads the next box header, returning `None` when EOF is reached before the
+/// full 8-byte header (a truncated header is treated the same as a clean EOF).
+async fn read_box_header<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<BoxHeader>> {
+    let mut head = [0u8; 8];
+    if let Err(err) = reader.read_exact(&mut head).await {
+        return if err.kind() == std::io::ErrorKind::UnexpectedEof {
+            Ok(None)
+        } else {
+            Err(err.into())
+        };
+    }
+
+    let mut size = u32::from_be_bytes(head[0..4].try_into().unwrap()) as u64;
+    let name = BoxType::from(u32::from_be_bytes(head[4..8].try_into().unwrap()));
+
+    if size == 1 {
+        let mut large_size = [0u8; 8];
+        reader.read_exact(&mut large_size).await?;
+        size = u64::from_be_bytes(large_size);
+    }
+
+    Ok(Some(BoxHeader::new(name, size)))
+}
+
+/// Reads a box's body (`header.size - HEADER_SIZE` bytes) into `buf`.
+async fn read_box_body<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    header: &BoxHeader,
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    if header.size < mp4::HEADER_SIZE {
+        return Err(Fmp4ParseError::InvalidFormat(
+            "Box size is smaller than the box header",
+        ));
+    }
+
+    buf.clear();
+    buf.resize((header.size - mp4::HEADER_SIZE) as usize, 0);
+    reader.read_exact(buf).await?;
+
+    Ok(())
+}
+
+impl AsyncSegment for InitialSegment {
+    async fn read<R: AsyncRead + Unpin + Send>(reader: &mut R) -> Result<Self> {
+        let mut data = Self::default();
+        let mut buf = Vec::new();
+
+        while let Some(header) = read_box_header(reader).await? {
+            match header.name {
+                BoxType::FtypBox => {
+                    read_box_body(reader, &header, &mut buf).await?;
+                    data.ftyp = FtypBox::read_box(&mut Cursor::new(&buf), header.size)?;
+                }
+                BoxType::MoovBox => {
+                    read_box_body(reader, &header, &mut buf).await?;
+                    data.moov = MoovBox::read_box(&mut Cursor::new(&buf), header.size)?;
+                }
+                _ => {
+                    read_box_body(reader, &header, &mut buf).await?;
+                    data.unknown_boxes.push((header.name, buf.clone()));
+                }
+            }
+        }
+
+        if data.moov.mvex.is_none() {
+            Err(Fmp4ParseError::InvalidFormat(
+                "Fmp4 initial segment must be set MvexBox.",
+            ))
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+impl AsyncSegment for MediaSegment {
+    async fn read<R: AsyncRead + Unpin + Send>(reader: &mut R) -> Result<Self> {
+        let mut media = Self::default();
+        let mut buf = Vec::new();
+
+        while let Some(header) = read_box_header(reader).await? {
+            match header.name {
+                BoxType::StypBox => {
+                    read_box_body(reader, &header, &mut buf).await?;
+                    media.styp = StypBox::read_box(&mut Cursor::new(&buf), header.size)?;
+                }
+                BoxType::SidxBox => {
+                    read_box_body(reader, &header, &mut buf).await?;
+                    media.sidx = Some(SidxBox::read_box(&mut Cursor::new(&buf), header.size)?);
+                    media.sidx_position = media.chunks.len();
+                    media.leading_order.push(LeadingBox::Sidx);
+                }
+                BoxType::EmsgBox => {
+                    read_box_body(reader, &header, &mut buf).await?;
+                    media.emsgs.push(EmsgBox::read_box(&mut Cursor::new(&buf), header.size)?);
+                    media.emsg_positions.push(media.chunks.len());
+                    media
+                        .leading_order
+                        .push(LeadingBox::Emsg(media.emsgs.len() - 1));
+                }
+                BoxType::MoofBox => {
+                    read_box_body(reader, &header, &mut buf).await?;
+                    let mut chunk = Chunk::default();
+                    chunk.moof = MoofBox::read_box(&mut Cursor::new(&buf), header.size)?;
+
+                    let Some(mdat_header) = read_box_header(reader).await? else {
+                        return Err(Fmp4ParseError::InvalidFormat(
+                            "MdatBox should be after MoofBox in the media segment",
+                        ));
+                    };
+
+                    if mdat_header.name != BoxType::MdatBox {
+                        return Err(Fmp4ParseError::InvalidFormat(
+                            "MdatBox should be after MoofBox in the media segment",
+                        ));
+                    }
+
+                    if mdat_header.size < mp4::HEADER_SIZE {
+                        return Err(Fmp4ParseError::InvalidFormat(
+                            "Box size is smaller than the box header",
+                        ));
+                    }
+
+                    let mut mdat = vec![0u8; (mdat_header.size - mp4::HEADER_SIZE) as usize];
+                    reader.read_exact(&mut mdat).await?;
+                    chunk.mdat = mdat;
+
+                    media.chunks.push(chunk);
+                }
+                _ => {
+                    read_box_body(reader, &header, &mut buf).await?;
+                    media
+                        .unknown_boxes
+                        .push((media.chunks.len(), header.name, buf.clone()));
+                    media
+                        .leading_order
+                        .push(LeadingBox::Unknown(media.unknown_boxes.len() - 1));
+                }
+            }
+        }
+
+        Ok(media)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mp4::MvexBox;
+
+    use crate::segment::Segment;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_async_initial_segment_matches_sync_read() {
+        let mut sync = InitialSegment::default();
+        sync.moov.mvex = Some(MvexBox::default());
+        sync.unknown_boxes
+            .push((BoxType::PrftBox, b"prft-payload".to_vec()));
+
+        let mut data = Vec::new();
+        <InitialSegment as Segment>::write(&sync, &mut data).expect("sync write should succeed");
+
+        let mut async_reader = Cursor::new(data.clone());
+        let async_parsed = <InitialSegment as AsyncSegment>::read(&mut async_reader)
+            .await
+            .expect("async read should succeed");
+
+        let mut sync_reader = Cursor::new(data);
+        let sync_parsed = <InitialSegment as Segment>::read(&mut sync_reader)
+            .expect("sync read should succeed");
+
+        assert_eq!(async_parsed, sync_parsed);
+    }
+
+    #[tokio::test]
+    async fn test_async_media_segment_matches_sync_read() {
+        let mut sync = MediaSegment::default();
+        sync.chunks.push(Chunk::default());
+        sync.sidx = Some(SidxBox::default());
+        sync.sidx_position = 0;
+        sync.leading_order.push(LeadingBox::Sidx);
+        sync.emsgs.push(EmsgBox::default());
+        sync.emsg_positions.push(1);
+        sync.leading_order.push(LeadingBox::Emsg(0));
+        sync.unknown_boxes
+            .push((1, BoxType::FreeBox, b"free-payload".to_vec()));
+        sync.leading_order.push(LeadingBox::Unknown(0));
+
+        let mut data = Vec::new();
+        <MediaSegment as Segment>::write(&sync, &mut data).expect("sync write should succeed");
+
+        let mut async_reader = Cursor::new(data.clone());
+        let async_parsed = <MediaSegment as AsyncSegment>::read(&mut async_reader)
+            .await
+            .expect("async read should succeed");
+
+        let mut sync_reader = Cursor::new(data);
+        let sync_parsed = <MediaSegment as Segment>::read(&mut sync_reader)
+            .expect("sync read should succeed");
+
+        assert_eq!(async_parsed, sync_parsed);
+    }
+
+    #[tokio::test]
+    async fn test_read_box_body_rejects_header_smaller_than_header_size() {
+        let header = BoxHeader::new(BoxType::FreeBox, 4);
+        let mut reader = Cursor::new(Vec::<u8>::new());
+        let mut buf = Vec::new();
+
+        assert!(read_box_body(&mut reader, &header, &mut buf).await.is_err());
+    }
+}
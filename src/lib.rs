@@ -1,9 +1,13 @@
+#[cfg(feature = "tokio")]
+mod async_segment;
 mod error;
 mod segment;
 mod writer;
 
 pub type Result<T> = std::result::Result<T, Fmp4ParseError>;
 
+#[cfg(feature = "tokio")]
+pub use async_segment::AsyncSegment;
 pub use error::Fmp4ParseError;
-pub use segment::{Chunk, InitialSegment, MediaSegment, Segment};
-pub use writer::{FMp4Config, HybridMp4Writer};
+pub use segment::{Chunk, InitialSegment, MediaSegment, SampleRef, Segment};
+pub use writer::{ChunkOffsetMode, FMp4Config, HybridMp4Writer};
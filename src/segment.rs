@@ -4,13 +4,16 @@ use std::{
 };
 
 use mp4::{
-    stsc::StscEntry, stts::SttsEntry, BoxHeader, BoxType, FtypBox, MoofBox, MoovBox, Mp4Box,
-    ReadBox, StypBox, WriteBox,
+    ctts::CttsEntry, stsc::StscEntry, stts::SttsEntry, BoxHeader, BoxType, EmsgBox, FtypBox,
+    MoofBox, MoovBox, Mp4Box, ReadBox, SidxBox, StypBox, WriteBox,
 };
 use serde::Serialize;
 
 use crate::{error::Fmp4ParseError, Result};
 
+/// `tfhd`/`trun` sample_flags bit indicating the sample is *not* a sync sample.
+const SAMPLE_IS_NON_SYNC_SAMPLE: u32 = 0x00010000;
+
 pub trait Segment: Sized {
     fn read<R: Read + Seek>(reader: &mut R) -> Result<Self>;
     fn write<W: Write>(&self, writer: &mut W) -> Result<()>;
@@ -20,6 +23,9 @@ pub trait Segment: Sized {
 pub struct InitialSegment {
     pub ftyp: FtypBox,
     pub moov: MoovBox,
+    /// Top-level boxes this crate doesn't model (e.g. vendor atoms), captured
+    /// verbatim in encounter order so `write` can round-trip them unchanged.
+    pub unknown_boxes: Vec<(BoxType, Vec<u8>)>,
 }
 
 impl Segment for InitialSegment {
@@ -35,7 +41,9 @@ impl Segment for InitialSegment {
                     data.moov = MoovBox::read_box(reader, header.size)?;
                 }
                 _ => {
-                    mp4::skip_box(reader, header.size)?;
+                    let mut raw = vec![0u8; header.size as usize - 8];
+                    reader.read_exact(&mut raw)?;
+                    data.unknown_boxes.push((header.name, raw));
                 }
             }
         }
@@ -53,6 +61,11 @@ impl Segment for InitialSegment {
         self.ftyp.write_box(writer)?;
         self.moov.write_box(writer)?;
 
+        for (name, raw) in self.unknown_boxes.iter() {
+            BoxHeader::new(*name, mp4::HEADER_SIZE + raw.len() as u64).write(writer)?;
+            writer.write_all(raw)?;
+        }
+
         Ok(())
     }
 }
@@ -97,10 +110,48 @@ impl fmt::Display for Chunk {
     }
 }
 
+/// A single sample borrowed from a [`Chunk::mdat`], for random access without copying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRef<'a> {
+    pub data: &'a [u8],
+    pub size: u32,
+    pub duration: u32,
+    pub composition_offset: i32,
+    pub sync: bool,
+}
+
+/// References one entry of `unknown_boxes` or `emsgs`, or the `sidx`, from
+/// `leading_order`, preserving the exact relative order boxes were read in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) enum LeadingBox {
+    Unknown(usize),
+    Emsg(usize),
+    Sidx,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub struct MediaSegment {
     pub styp: StypBox,
     pub chunks: Vec<Chunk>,
+    /// Segment Index box, present ahead of the first fragment in DASH/CMAF delivery.
+    pub sidx: Option<SidxBox>,
+    /// Index into `chunks` `sidx` precedes (`chunks.len()` meaning "after the
+    /// last chunk"), meaningful only when `sidx` is `Some`.
+    pub(crate) sidx_position: usize,
+    /// In-band event messages, each positioned ahead of the fragment it annotates.
+    pub emsgs: Vec<EmsgBox>,
+    /// Index into `chunks` each entry of `emsgs` precedes (`chunks.len()` meaning
+    /// "after the last chunk"), parallel to `emsgs`.
+    pub(crate) emsg_positions: Vec<usize>,
+    /// Top-level boxes this crate doesn't model (e.g. `prft`, vendor atoms),
+    /// captured verbatim and positioned by the index of the chunk they
+    /// precede (`chunks.len()` meaning "after the last chunk") so `write`
+    /// can round-trip their original placement.
+    pub unknown_boxes: Vec<(usize, BoxType, Vec<u8>)>,
+    /// Encounter order of `sidx`, `unknown_boxes`, and `emsgs`, so `write` can
+    /// emit them in exactly the order they were read rather than always
+    /// emitting one kind before another at a shared position.
+    pub(crate) leading_order: Vec<LeadingBox>,
 }
 
 impl MediaSegment {
@@ -200,12 +251,234 @@ impl MediaSegment {
         entries
     }
 
+    /// Sync-sample table
+    pub fn stss_entries(
+        &self,
+        track_id: u32,
+        default_sample_flags: u32,
+        sample_base: u32,
+    ) -> Vec<u32> {
+        let mut entries: Vec<u32> = Vec::new();
+        let mut sample_number = sample_base;
+
+        for chunk in self.chunks.iter() {
+            let Some(traf) = chunk
+                .moof
+                .trafs
+                .iter()
+                .find(|traf| traf.tfhd.track_id == track_id)
+            else {
+                continue;
+            };
+            let Some(trun) = traf.trun.as_ref() else {
+                continue;
+            };
+
+            for i in 0..trun.sample_count as usize {
+                let flags = trun
+                    .sample_flags
+                    .get(i)
+                    .copied()
+                    .or_else(|| if i == 0 { trun.first_sample_flags } else { None })
+                    .or(traf.tfhd.default_sample_flags)
+                    .unwrap_or(default_sample_flags);
+
+                if flags & SAMPLE_IS_NON_SYNC_SAMPLE == 0 {
+                    entries.push(sample_number);
+                }
+
+                sample_number += 1;
+            }
+        }
+
+        entries
+    }
+
+    /// Composition-time-offset table
+    pub fn ctts_entries(&self, track_id: u32) -> (Vec<CttsEntry>, bool) {
+        let mut entries: Vec<CttsEntry> = Vec::new();
+        let mut signed = false;
+
+        for chunk in self.chunks.iter() {
+            let Some(traf) = chunk
+                .moof
+                .trafs
+                .iter()
+                .find(|traf| traf.tfhd.track_id == track_id)
+            else {
+                continue;
+            };
+            let Some(trun) = traf.trun.as_ref() else {
+                continue;
+            };
+
+            if trun.version == 1 {
+                signed = true;
+            }
+
+            for i in 0..trun.sample_count as usize {
+                let sample_offset = trun.sample_cts.get(i).copied().unwrap_or(0);
+                if sample_offset < 0 {
+                    signed = true;
+                }
+
+                match entries.last_mut() {
+                    Some(last) if last.sample_offset == sample_offset => {
+                        last.sample_count += 1;
+                    }
+                    _ => entries.push(CttsEntry {
+                        sample_count: 1,
+                        sample_offset,
+                    }),
+                }
+            }
+        }
+
+        (entries, signed)
+    }
+
+    /// Per-sample accessor for random access into this segment's `mdat`s.
+    pub fn samples(
+        &self,
+        track_id: u32,
+        default_sample_duration: u32,
+        default_sample_size: u32,
+        default_sample_flags: u32,
+    ) -> Result<impl Iterator<Item = SampleRef<'_>> + '_> {
+        if !self
+            .chunks
+            .iter()
+            .any(|chunk| chunk.moof.trafs.iter().any(|traf| traf.tfhd.track_id == track_id))
+        {
+            return Err(Fmp4ParseError::InvalidFormat(
+                "No sample data for the given track_id",
+            ));
+        }
+
+        let mut samples: Vec<SampleRef<'_>> = Vec::new();
+
+        for chunk in self.chunks.iter() {
+            let Some(traf) = chunk
+                .moof
+                .trafs
+                .iter()
+                .find(|traf| traf.tfhd.track_id == track_id)
+            else {
+                continue;
+            };
+            let Some(trun) = traf.trun.as_ref() else {
+                continue;
+            };
+
+            // `trun.data_offset` is relative to the start of the enclosing moof;
+            // translate it into an offset within `chunk.mdat`, defaulting to the
+            // first byte after the mdat header when the trun doesn't set one.
+            let mdat_start = (chunk.moof.box_size() + mp4::HEADER_SIZE) as i64;
+            let mut pos = trun
+                .data_offset
+                .map(|offset| (offset as i64 - mdat_start).max(0))
+                .unwrap_or(0) as usize;
+
+            for i in 0..trun.sample_count as usize {
+                let size = trun
+                    .sample_sizes
+                    .get(i)
+                    .copied()
+                    .unwrap_or_else(|| traf.tfhd.default_sample_size.unwrap_or(default_sample_size))
+                    as usize;
+
+                let data = chunk.mdat.get(pos..pos + size).ok_or(
+                    Fmp4ParseError::InvalidFormat("Sample byte range exceeds mdat"),
+                )?;
+
+                let duration = trun.sample_durations.get(i).copied().unwrap_or_else(|| {
+                    traf.tfhd
+                        .default_sample_duration
+                        .unwrap_or(default_sample_duration)
+                });
+
+                let composition_offset = trun.sample_cts.get(i).copied().unwrap_or(0);
+
+                let flags = trun
+                    .sample_flags
+                    .get(i)
+                    .copied()
+                    .or_else(|| if i == 0 { trun.first_sample_flags } else { None })
+                    .or(traf.tfhd.default_sample_flags)
+                    .unwrap_or(default_sample_flags);
+
+                samples.push(SampleRef {
+                    data,
+                    size: size as u32,
+                    duration,
+                    composition_offset,
+                    sync: flags & SAMPLE_IS_NON_SYNC_SAMPLE == 0,
+                });
+
+                pos += size;
+            }
+        }
+
+        Ok(samples.into_iter())
+    }
+
+    /// Indexed convenience wrapper over [`Self::samples`].
+    pub fn sample(
+        &self,
+        track_id: u32,
+        index: usize,
+        default_sample_duration: u32,
+        default_sample_size: u32,
+        default_sample_flags: u32,
+    ) -> Result<SampleRef<'_>> {
+        self.samples(
+            track_id,
+            default_sample_duration,
+            default_sample_size,
+            default_sample_flags,
+        )?
+        .nth(index)
+        .ok_or(Fmp4ParseError::InvalidFormat("Sample index out of range"))
+    }
+
     pub fn get_size(&self) -> u64 {
         self.chunks
             .iter()
             .map(|chunk| chunk.moof.box_size() + mp4::HEADER_SIZE + chunk.mdat.len() as u64)
             .sum()
     }
+
+    /// Writes the unknown boxes, emsgs and sidx positioned immediately before chunk
+    /// `idx` (or trailing the segment, when `idx == self.chunks.len()`), in
+    /// `leading_order` so interleaved reads round-trip in their original order.
+    fn write_leading_before<W: Write>(&self, idx: usize, writer: &mut W) -> Result<()> {
+        for entry in self.leading_order.iter() {
+            match *entry {
+                LeadingBox::Unknown(i) => {
+                    let (pos, name, raw) = &self.unknown_boxes[i];
+                    if *pos == idx {
+                        BoxHeader::new(*name, mp4::HEADER_SIZE + raw.len() as u64)
+                            .write(writer)?;
+                        writer.write_all(raw)?;
+                    }
+                }
+                LeadingBox::Emsg(i) => {
+                    if self.emsg_positions[i] == idx {
+                        self.emsgs[i].write_box(writer)?;
+                    }
+                }
+                LeadingBox::Sidx => {
+                    if self.sidx_position == idx {
+                        if let Some(sidx) = &self.sidx {
+                            sidx.write_box(writer)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Segment for MediaSegment {
@@ -217,6 +490,18 @@ impl Segment for MediaSegment {
                 BoxType::StypBox => {
                     media.styp = StypBox::read_box(reader, header.size)?;
                 }
+                BoxType::SidxBox => {
+                    media.sidx = Some(SidxBox::read_box(reader, header.size)?);
+                    media.sidx_position = media.chunks.len();
+                    media.leading_order.push(LeadingBox::Sidx);
+                }
+                BoxType::EmsgBox => {
+                    media.emsgs.push(EmsgBox::read_box(reader, header.size)?);
+                    media.emsg_positions.push(media.chunks.len());
+                    media
+                        .leading_order
+                        .push(LeadingBox::Emsg(media.emsgs.len() - 1));
+                }
                 BoxType::MoofBox => {
                     let mut chunk = Chunk::default();
                     chunk.moof = MoofBox::read_box(reader, header.size)?;
@@ -236,7 +521,14 @@ impl Segment for MediaSegment {
                     media.chunks.push(chunk);
                 }
                 _ => {
-                    mp4::skip_box(reader, header.size)?;
+                    let mut raw = vec![0u8; header.size as usize - 8];
+                    reader.read_exact(&mut raw)?;
+                    media
+                        .unknown_boxes
+                        .push((media.chunks.len(), header.name, raw));
+                    media
+                        .leading_order
+                        .push(LeadingBox::Unknown(media.unknown_boxes.len() - 1));
                 }
             }
         }
@@ -247,9 +539,11 @@ impl Segment for MediaSegment {
     fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
         self.styp.write_box(writer)?;
 
-        for chunk in self.chunks.iter() {
+        for (idx, chunk) in self.chunks.iter().enumerate() {
+            self.write_leading_before(idx, writer)?;
             chunk.write(writer)?;
         }
+        self.write_leading_before(self.chunks.len(), writer)?;
 
         Ok(())
     }
@@ -328,4 +622,243 @@ mod tests {
 
         std::fs::remove_file(copy_path).unwrap();
     }
+
+    #[test]
+    fn test_media_segment_roundtrips_unknown_boxes() {
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk::default());
+        media.unknown_boxes.push((0, BoxType::PrftBox, b"prft-payload".to_vec()));
+        media.leading_order.push(LeadingBox::Unknown(0));
+        media.unknown_boxes.push((1, BoxType::FreeBox, b"free-payload".to_vec()));
+        media.leading_order.push(LeadingBox::Unknown(1));
+
+        let mut data = Vec::new();
+        media.write(&mut data).expect("Failed to write media segment");
+
+        let mut reader = Cursor::new(data.clone());
+        let copy_media = MediaSegment::read(&mut reader).expect("Failed to parse media segment");
+        assert_eq!(media, copy_media);
+
+        let mut copy_data = Vec::new();
+        copy_media
+            .write(&mut copy_data)
+            .expect("Failed to write media segment");
+        assert_eq!(data, copy_data);
+    }
+
+    #[test]
+    fn test_media_segment_roundtrips_sidx_and_emsg() {
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk::default());
+        media.sidx = Some(SidxBox::default());
+        media.sidx_position = 0;
+        media.leading_order.push(LeadingBox::Sidx);
+        media.emsgs.push(EmsgBox::default());
+        media.emsg_positions.push(0);
+        media.leading_order.push(LeadingBox::Emsg(0));
+
+        let mut data = Vec::new();
+        media.write(&mut data).expect("Failed to write media segment");
+
+        let mut reader = Cursor::new(data);
+        let copy_media = MediaSegment::read(&mut reader).expect("Failed to parse media segment");
+
+        assert!(copy_media.sidx.is_some());
+        assert_eq!(copy_media.emsgs.len(), 1);
+        assert_eq!(media, copy_media);
+    }
+
+    #[test]
+    fn test_media_segment_preserves_order_of_boxes_preceding_sidx() {
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk::default());
+
+        // Read order at position 0: an unrecognized box and an emsg both
+        // precede sidx, which must not jump ahead of them on write.
+        media.unknown_boxes.push((0, BoxType::PrftBox, b"prft-payload".to_vec()));
+        media.leading_order.push(LeadingBox::Unknown(0));
+
+        media.emsgs.push(EmsgBox::default());
+        media.emsg_positions.push(0);
+        media.leading_order.push(LeadingBox::Emsg(0));
+
+        media.sidx = Some(SidxBox::default());
+        media.sidx_position = 0;
+        media.leading_order.push(LeadingBox::Sidx);
+
+        let mut data = Vec::new();
+        media.write(&mut data).expect("Failed to write media segment");
+
+        let mut reader = Cursor::new(data);
+        let copy_media = MediaSegment::read(&mut reader).expect("Failed to parse media segment");
+
+        assert_eq!(
+            copy_media.leading_order,
+            vec![
+                LeadingBox::Unknown(0),
+                LeadingBox::Emsg(0),
+                LeadingBox::Sidx,
+            ]
+        );
+        assert_eq!(media, copy_media);
+    }
+
+    #[test]
+    fn test_stss_entries_resolves_trun_tfhd_trex_precedence() {
+        let mut trun = mp4::TrunBox::default();
+        trun.sample_count = 3;
+        trun.sample_flags = vec![0, SAMPLE_IS_NON_SYNC_SAMPLE];
+
+        let mut tfhd = mp4::TfhdBox::default();
+        tfhd.track_id = 1;
+        tfhd.default_sample_flags = Some(SAMPLE_IS_NON_SYNC_SAMPLE);
+
+        let mut traf = mp4::TrafBox::default();
+        traf.tfhd = tfhd;
+        traf.trun = Some(trun);
+
+        let mut moof = MoofBox::default();
+        moof.trafs.push(traf);
+
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk { moof, mdat: Vec::new() });
+
+        // Sample 1 is sync (explicit trun flag), sample 2 is non-sync (explicit
+        // trun flag), sample 3 falls through to tfhd's default (non-sync).
+        assert_eq!(media.stss_entries(1, 0, 1), vec![1]);
+    }
+
+    #[test]
+    fn test_ctts_entries_coalesces_runs_and_detects_signed_offsets() {
+        let mut trun = mp4::TrunBox::default();
+        trun.sample_count = 4;
+        trun.sample_cts = vec![0, 0, -5, -5];
+
+        let mut tfhd = mp4::TfhdBox::default();
+        tfhd.track_id = 1;
+
+        let mut traf = mp4::TrafBox::default();
+        traf.tfhd = tfhd;
+        traf.trun = Some(trun);
+
+        let mut moof = MoofBox::default();
+        moof.trafs.push(traf);
+
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk { moof, mdat: Vec::new() });
+
+        let (entries, signed) = media.ctts_entries(1);
+        assert!(signed);
+        assert_eq!(
+            entries,
+            vec![
+                CttsEntry { sample_count: 2, sample_offset: 0 },
+                CttsEntry { sample_count: 2, sample_offset: -5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_samples_slices_mdat_in_order() {
+        let mut trun = mp4::TrunBox::default();
+        trun.sample_count = 2;
+        trun.sample_sizes = vec![4, 3];
+        trun.sample_durations = vec![10, 20];
+        trun.sample_cts = vec![0, 1];
+        trun.sample_flags = vec![0, SAMPLE_IS_NON_SYNC_SAMPLE];
+
+        let mut tfhd = mp4::TfhdBox::default();
+        tfhd.track_id = 1;
+
+        let mut traf = mp4::TrafBox::default();
+        traf.tfhd = tfhd;
+        traf.trun = Some(trun);
+
+        let mut moof = MoofBox::default();
+        moof.trafs.push(traf);
+
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk {
+            moof,
+            mdat: b"abcdefg".to_vec(),
+        });
+
+        let samples: Vec<_> = media.samples(1, 0, 0, 0).expect("track_id 1 exists").collect();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].data, b"abcd");
+        assert_eq!(samples[0].duration, 10);
+        assert_eq!(samples[0].composition_offset, 0);
+        assert!(samples[0].sync);
+        assert_eq!(samples[1].data, b"efg");
+        assert_eq!(samples[1].duration, 20);
+        assert_eq!(samples[1].composition_offset, 1);
+        assert!(!samples[1].sync);
+
+        assert_eq!(media.sample(1, 1, 0, 0, 0).expect("index 1 exists").data, b"efg");
+    }
+
+    #[test]
+    fn test_samples_errors_for_unknown_track_id() {
+        let media = MediaSegment::default();
+        assert!(media.samples(1, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_sample_errors_for_out_of_range_index() {
+        let mut trun = mp4::TrunBox::default();
+        trun.sample_count = 1;
+        trun.sample_sizes = vec![1];
+
+        let mut tfhd = mp4::TfhdBox::default();
+        tfhd.track_id = 1;
+
+        let mut traf = mp4::TrafBox::default();
+        traf.tfhd = tfhd;
+        traf.trun = Some(trun);
+
+        let mut moof = MoofBox::default();
+        moof.trafs.push(traf);
+
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk {
+            moof,
+            mdat: vec![0u8],
+        });
+
+        assert!(media.sample(1, 5, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_media_segment_preserves_unknown_box_and_emsg_interleave_order() {
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk::default());
+
+        // Read order at position 0: emsg, then unknown box, then a second emsg.
+        media.emsgs.push(EmsgBox::default());
+        media.emsg_positions.push(0);
+        media.leading_order.push(LeadingBox::Emsg(0));
+
+        media.unknown_boxes.push((0, BoxType::PrftBox, b"prft-payload".to_vec()));
+        media.leading_order.push(LeadingBox::Unknown(0));
+
+        media.emsgs.push(EmsgBox::default());
+        media.emsg_positions.push(0);
+        media.leading_order.push(LeadingBox::Emsg(1));
+
+        let mut data = Vec::new();
+        media.write(&mut data).expect("Failed to write media segment");
+
+        let mut reader = Cursor::new(data);
+        let copy_media = MediaSegment::read(&mut reader).expect("Failed to parse media segment");
+
+        assert_eq!(
+            copy_media.leading_order,
+            vec![
+                LeadingBox::Emsg(0),
+                LeadingBox::Unknown(0),
+                LeadingBox::Emsg(1),
+            ]
+        );
+        assert_eq!(media, copy_media);
+    }
 }
@@ -6,6 +6,8 @@ use std::{
 
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use mp4::*;
+use ctts::CttsEntry;
+use sidx::SidxEntry;
 use stsc::StscEntry;
 use stts::SttsEntry;
 
@@ -68,7 +70,7 @@ pub struct TrackExtendData {
     pub default_sample_description_index: u32,
     pub default_sample_duration: u32,
     pub default_sample_size: u32,
-    pub _default_sample_flags: u32,
+    pub default_sample_flags: u32,
 }
 
 impl From<&MvexBox> for TrackExtendData {
@@ -78,7 +80,7 @@ impl From<&MvexBox> for TrackExtendData {
             default_sample_description_index: value.trex.default_sample_description_index,
             default_sample_duration: value.trex.default_sample_duration,
             default_sample_size: value.trex.default_sample_size,
-            _default_sample_flags: value.trex.default_sample_flags,
+            default_sample_flags: value.trex.default_sample_flags,
         }
     }
 }
@@ -114,11 +116,31 @@ impl TryFrom<&MoovBox> for TrackData {
     }
 }
 
+/// Controls which chunk-offset box(es) `HybridMp4Writer::finalize` emits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkOffsetMode {
+    /// Emit `stco` when every chunk offset fits in `u32`, otherwise `co64`.
+    #[default]
+    Auto,
+    /// Always emit `stco`, erroring if an offset exceeds 32 bits.
+    ForceStco,
+    /// Always emit `co64`.
+    ForceCo64,
+}
+
+/// Size (bytes) of a single `sidx` reference entry.
+const SIDX_ENTRY_SIZE: u64 = 12;
+
 #[derive(Debug, Default)]
 pub struct FMp4Config {
     pub major_brand: FourCC,
     pub minor_version: u32,
     pub compatible_brands: Vec<FourCC>,
+    pub chunk_offset_mode: ChunkOffsetMode,
+    /// Synthesizes a whole-file `sidx` referencing each ingested fragment when
+    /// greater than zero, reserving space for up to this many fragments right
+    /// after `ftyp`. Zero (the default) disables `sidx` synthesis.
+    pub sidx_fragment_capacity: u32,
     tracks: HashMap<u32, TrackData>,
 }
 
@@ -137,6 +159,9 @@ pub struct Track {
     stts_entries: Vec<SttsEntry>,
     stsc_entries: Vec<StscEntry>,
     stsz_entries: Vec<u32>,
+    stss_entries: Vec<u32>,
+    ctts_entries: Vec<CttsEntry>,
+    ctts_signed: bool,
     co64_entries: Vec<u64>,
     chunk_offset: u32,
     sample_offset: u32,
@@ -147,6 +172,12 @@ pub struct HybridMp4Writer<W> {
     writer: W,
     free_pos: u64,
     free_size: u64,
+    chunk_offset_mode: ChunkOffsetMode,
+    /// Position and reserved byte count of the placeholder left for a
+    /// synthesized `sidx`, set only when `sidx_fragment_capacity > 0`.
+    sidx_reservation: Option<(u64, u64)>,
+    sidx_reference_track: Option<u32>,
+    sidx_entries: Vec<SidxEntry>,
     tracks: HashMap<u32, Track>,
 }
 
@@ -159,6 +190,25 @@ impl<W: Write + Seek> HybridMp4Writer<W> {
         };
         ftyp.write_box(&mut writer)?;
 
+        // The sidx reservation (if any) is written before `free_pos` so the
+        // giant mdat placeholder starts exactly where fragment bytes will
+        // begin; `free_size` only ever accumulates fragment bytes, so it must
+        // never need to cover the sidx reservation's span.
+        let sidx_reservation = if config.sidx_fragment_capacity > 0 {
+            let pos = writer.stream_position()?;
+            // Derive the fixed portion from an actual empty `sidx`'s size rather
+            // than a hand-maintained constant, so this can't drift from the
+            // `mp4` crate's real box layout.
+            let reserved = SidxBox::default().box_size()
+                + config.sidx_fragment_capacity as u64 * SIDX_ENTRY_SIZE;
+            BoxHeader::new(BoxType::FreeBox, reserved).write(&mut writer)?;
+            writer.write_all(&vec![0u8; (reserved - HEADER_SIZE) as usize])?;
+
+            Some((pos, reserved))
+        } else {
+            None
+        };
+
         let free_pos = writer.stream_position()?;
         BoxHeader::new(BoxType::FreeBox, 0).write(&mut writer)?;
 
@@ -172,6 +222,9 @@ impl<W: Write + Seek> HybridMp4Writer<W> {
                     stts_entries: Vec::new(),
                     stsc_entries: Vec::new(),
                     stsz_entries: Vec::new(),
+                    stss_entries: Vec::new(),
+                    ctts_entries: Vec::new(),
+                    ctts_signed: false,
                     co64_entries: Vec::new(),
                     chunk_offset: 1,
                     sample_offset: 1,
@@ -183,16 +236,56 @@ impl<W: Write + Seek> HybridMp4Writer<W> {
             writer,
             free_pos,
             free_size: 0,
+            chunk_offset_mode: config.chunk_offset_mode,
+            sidx_reservation,
+            sidx_reference_track: config.tracks.keys().min().copied(),
+            sidx_entries: Vec::new(),
             tracks,
         })
     }
 
     pub fn add_fragment(&mut self, media: MediaSegment) -> Result<()> {
+        if self.sidx_reservation.is_some() {
+            if let Some(track_id) = self.sidx_reference_track {
+                if let Some(track) = self.tracks.get(&track_id) {
+                    // Sum each sample's actual resolved duration (rather than
+                    // `stts_entries`' one-delta-per-fragment aggregate) so
+                    // fragments with variable per-sample durations still
+                    // produce an accurate `sidx.subsegment_duration`.
+                    let subsegment_duration = media
+                        .samples(
+                            track_id,
+                            track.data.extend.default_sample_duration,
+                            track.data.extend.default_sample_size,
+                            track.data.extend.default_sample_flags,
+                        )
+                        .map(|samples| samples.map(|sample| sample.duration as u64).sum::<u64>())
+                        .unwrap_or(0) as u32;
+
+                    let starts_with_sap = media
+                        .stss_entries(track_id, track.data.extend.default_sample_flags, 1)
+                        .first()
+                        .copied()
+                        == Some(1);
+
+                    self.sidx_entries.push(SidxEntry {
+                        reference_type: 0,
+                        referenced_size: media.get_size() as u32,
+                        subsegment_duration,
+                        starts_with_sap,
+                        sap_type: if starts_with_sap { 1 } else { 0 },
+                        sap_delta_time: 0,
+                    });
+                }
+            }
+        }
+
         for (track_id, track) in self.tracks.iter_mut() {
             track
                 .stts_entries
                 .extend(media.stts_entries(*track_id, track.data.extend.default_sample_duration));
 
+            let sample_base = track.sample_offset;
             let (stsc_entries, chunk_count, sample_count) = media.stsc_entries(
                 *track_id,
                 track.data.extend.default_sample_description_index,
@@ -207,6 +300,23 @@ impl<W: Write + Seek> HybridMp4Writer<W> {
                 .stsz_entries
                 .extend(media.stsz_entries(*track_id, track.data.extend.default_sample_size));
 
+            track.stss_entries.extend(media.stss_entries(
+                *track_id,
+                track.data.extend.default_sample_flags,
+                sample_base,
+            ));
+
+            let (ctts_entries, signed) = media.ctts_entries(*track_id);
+            track.ctts_signed |= signed;
+            for entry in ctts_entries {
+                match track.ctts_entries.last_mut() {
+                    Some(last) if last.sample_offset == entry.sample_offset => {
+                        last.sample_count += entry.sample_count;
+                    }
+                    _ => track.ctts_entries.push(entry),
+                }
+            }
+
             for chunk in media.chunks.iter() {
                 chunk.moof.write_box(&mut self.writer)?;
 
@@ -282,12 +392,28 @@ impl<W: Write + Seek> HybridMp4Writer<W> {
                 MediaBox::__Unknown => {}
             }
 
+            let total_samples = track.stsz_entries.len();
+
             let mut stts = SttsBox::default();
             stts.entries.append(&mut track.stts_entries);
             trak.mdia.minf.stbl.stts = stts;
 
-            // Need
-            trak.mdia.minf.stbl.stss = Some(StssBox::default());
+            // Omit stss entirely when every sample is a sync sample (e.g. audio-only
+            // tracks), matching the usual convention for all-keyframe media.
+            if track.stss_entries.len() < total_samples {
+                let mut stss = StssBox::default();
+                stss.entries.append(&mut track.stss_entries);
+                trak.mdia.minf.stbl.stss = Some(stss);
+            }
+
+            // Omit ctts entirely when every sample has a zero composition offset,
+            // keeping audio-only output free of an unnecessary box.
+            if track.ctts_entries.iter().any(|entry| entry.sample_offset != 0) {
+                let mut ctts = CttsBox::default();
+                ctts.version = if track.ctts_signed { 1 } else { 0 };
+                ctts.entries.append(&mut track.ctts_entries);
+                trak.mdia.minf.stbl.ctts = Some(ctts);
+            }
 
             let mut stsc = StscBox::default();
             stsc.entries.append(&mut track.stsc_entries);
@@ -298,12 +424,34 @@ impl<W: Write + Seek> HybridMp4Writer<W> {
             stsz.sample_sizes.append(&mut track.stsz_entries);
             trak.mdia.minf.stbl.stsz = stsz;
 
-            let mut co64 = Co64Box::default();
-            co64.entries.append(&mut track.co64_entries);
-            trak.mdia.minf.stbl.co64 = Some(co64);
-
-            // stco and co64 never exist at the same time.
-            trak.mdia.minf.stbl.stco = None;
+            // Offsets into mdat are fixed before moov is written (moov comes last in
+            // this writer), so stco vs co64 can be decided from the already-collected
+            // offsets with no second pass.
+            let fits_u32 = track
+                .co64_entries
+                .iter()
+                .all(|&offset| u32::try_from(offset).is_ok());
+            let use_stco = match self.chunk_offset_mode {
+                ChunkOffsetMode::ForceStco => true,
+                ChunkOffsetMode::ForceCo64 => false,
+                ChunkOffsetMode::Auto => fits_u32,
+            };
+
+            if use_stco {
+                let mut stco = StcoBox::default();
+                for offset in track.co64_entries.drain(..) {
+                    stco.entries.push(u32::try_from(offset).map_err(|_| {
+                        Fmp4ParseError::InvalidFormat(
+                            "Chunk offset exceeds stco's 32-bit range",
+                        )
+                    })?);
+                }
+                trak.mdia.minf.stbl.stco = Some(stco);
+            } else {
+                let mut co64 = Co64Box::default();
+                co64.entries.append(&mut track.co64_entries);
+                trak.mdia.minf.stbl.co64 = Some(co64);
+            }
 
             moov.traks.push(trak);
 
@@ -316,6 +464,37 @@ impl<W: Write + Seek> HybridMp4Writer<W> {
         }
 
         moov.mvhd.duration = moov_duration * moov.mvhd.timescale as u64;
+
+        if let Some((pos, reserved)) = self.sidx_reservation {
+            let reference_track = self
+                .sidx_reference_track
+                .and_then(|track_id| self.tracks.get(&track_id));
+
+            let mut sidx = SidxBox::default();
+            sidx.reference_id = self.sidx_reference_track.unwrap_or(0);
+            sidx.timescale = reference_track.map(|track| track.data.base.timescale).unwrap_or(0);
+            sidx.entries.append(&mut self.sidx_entries);
+
+            let required = sidx.box_size();
+            if required > reserved {
+                return Err(Fmp4ParseError::InvalidFormat(
+                    "sidx_fragment_capacity too small for the number of ingested fragments",
+                ));
+            }
+
+            self.writer.seek(std::io::SeekFrom::Start(pos))?;
+            sidx.write_box(&mut self.writer)?;
+
+            let padding = reserved - required;
+            if padding > 0 {
+                BoxHeader::new(BoxType::FreeBox, padding).write(&mut self.writer)?;
+                self.writer
+                    .write_all(&vec![0u8; (padding - HEADER_SIZE) as usize])?;
+            }
+
+            self.writer.seek(std::io::SeekFrom::End(0))?;
+        }
+
         moov.write_box(&mut self.writer)?;
 
         self.writer.seek(std::io::SeekFrom::Start(self.free_pos))?;
@@ -325,3 +504,156 @@ impl<W: Write + Seek> HybridMp4Writer<W> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    use crate::segment::Chunk;
+
+    use super::*;
+
+    /// A `Write + Seek` handle over a shared buffer, so the bytes a
+    /// `HybridMp4Writer` produces can still be inspected after `finalize`
+    /// consumes the writer.
+    struct SharedCursor(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+    impl Write for SharedCursor {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.borrow_mut().flush()
+        }
+    }
+
+    impl Seek for SharedCursor {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.borrow_mut().seek(pos)
+        }
+    }
+
+    fn config_with_track(track_id: u32, mode: ChunkOffsetMode) -> FMp4Config {
+        let mut trak = TrakBox::default();
+        trak.tkhd.track_id = track_id;
+        trak.mdia.mdhd.timescale = 1000;
+
+        let mut mvex = MvexBox::default();
+        mvex.trex.track_id = track_id;
+
+        let mut moov = MoovBox::default();
+        moov.mvex = Some(mvex);
+        moov.traks.push(trak);
+
+        let initial_segment = InitialSegment {
+            moov,
+            ..Default::default()
+        };
+
+        let mut config = FMp4Config::default();
+        config.chunk_offset_mode = mode;
+        config
+            .add_track(&initial_segment)
+            .expect("initial segment has a matching trak and mvex");
+
+        config
+    }
+
+    fn fragment_with_sample_sizes(track_id: u32, sizes: &[u32]) -> MediaSegment {
+        let mut trun = TrunBox::default();
+        trun.sample_count = sizes.len() as u32;
+        trun.sample_sizes = sizes.to_vec();
+
+        let mut tfhd = TfhdBox::default();
+        tfhd.track_id = track_id;
+
+        let mut traf = TrafBox::default();
+        traf.tfhd = tfhd;
+        traf.trun = Some(trun);
+
+        let mut moof = MoofBox::default();
+        moof.trafs.push(traf);
+
+        let mdat = vec![0u8; sizes.iter().sum::<u32>() as usize];
+
+        let mut media = MediaSegment::default();
+        media.chunks.push(Chunk { moof, mdat });
+        media
+    }
+
+    fn new_writer(config: &FMp4Config) -> (HybridMp4Writer<SharedCursor>, Rc<RefCell<Cursor<Vec<u8>>>>) {
+        let shared = Rc::new(RefCell::new(Cursor::new(Vec::new())));
+        let writer = HybridMp4Writer::initialize(SharedCursor(shared.clone()), config)
+            .expect("initialize should succeed");
+        (writer, shared)
+    }
+
+    /// Returns whether the finalized output's single trak carries an `stco`
+    /// and/or `co64` chunk-offset box.
+    fn chunk_offset_boxes(bytes: &[u8]) -> (bool, bool) {
+        let mut reader = Cursor::new(bytes.to_vec());
+
+        while let Ok(header) = BoxHeader::read(&mut reader) {
+            if header.name == BoxType::MoovBox {
+                let moov = MoovBox::read_box(&mut reader, header.size).expect("valid moov");
+                let stbl = &moov.traks[0].mdia.minf.stbl;
+                return (stbl.stco.is_some(), stbl.co64.is_some());
+            }
+
+            reader
+                .seek(std::io::SeekFrom::Current(header.size as i64 - HEADER_SIZE as i64))
+                .expect("box body fits in the buffer");
+        }
+
+        panic!("no moov box found in finalized output");
+    }
+
+    #[test]
+    fn test_chunk_offset_mode_auto_uses_stco_for_small_offsets() {
+        let config = config_with_track(1, ChunkOffsetMode::Auto);
+        let (mut writer, shared) = new_writer(&config);
+        writer
+            .add_fragment(fragment_with_sample_sizes(1, &[4, 4]))
+            .expect("add_fragment should succeed");
+        writer.finalize().expect("finalize should succeed");
+
+        let (has_stco, has_co64) = chunk_offset_boxes(shared.borrow().get_ref());
+        assert!(has_stco);
+        assert!(!has_co64);
+    }
+
+    #[test]
+    fn test_chunk_offset_mode_force_co64_ignores_small_offsets() {
+        let config = config_with_track(1, ChunkOffsetMode::ForceCo64);
+        let (mut writer, shared) = new_writer(&config);
+        writer
+            .add_fragment(fragment_with_sample_sizes(1, &[4, 4]))
+            .expect("add_fragment should succeed");
+        writer.finalize().expect("finalize should succeed");
+
+        let (has_stco, has_co64) = chunk_offset_boxes(shared.borrow().get_ref());
+        assert!(!has_stco);
+        assert!(has_co64);
+    }
+
+    #[test]
+    fn test_chunk_offset_mode_force_stco_errors_when_offset_overflows_u32() {
+        let config = config_with_track(1, ChunkOffsetMode::ForceStco);
+        let (mut writer, _shared) = new_writer(&config);
+        writer
+            .add_fragment(fragment_with_sample_sizes(1, &[4]))
+            .expect("add_fragment should succeed");
+
+        // Inject an offset beyond stco's 32-bit range without actually
+        // writing gigabytes of fragment data.
+        writer
+            .tracks
+            .get_mut(&1)
+            .expect("track was registered")
+            .co64_entries
+            .push(u64::from(u32::MAX) + 1);
+
+        assert!(writer.finalize().is_err());
+    }
+}